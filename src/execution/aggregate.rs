@@ -19,8 +19,10 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::str;
 
-use arrow::array::{ArrayRef, Int32Array, Float64Array, BinaryArray};
-use arrow::array_ops;
+use arrow::array::{
+    ArrayRef, BinaryArray, BooleanArray, Float32Array, Float64Array, Int16Array, Int32Array,
+    Int64Array, Int8Array, UInt16Array, UInt32Array, UInt64Array, UInt8Array,
+};
 use arrow::datatypes::{Field, Schema, DataType};
 use arrow::record_batch::RecordBatch;
 
@@ -29,13 +31,56 @@ use super::expression::{RuntimeExpr, AggregateType};
 use crate::logicalplan::ScalarValue;
 use super::relation::Relation;
 
-use fnv::FnvHashMap;
+use fnv::{FnvHashMap, FnvHashSet};
+
+/// Factory for a user-defined aggregate function: produces a fresh, independent
+/// accumulator instance each time a new GROUP BY key (or a fresh ungrouped aggregate)
+/// needs one
+pub type AggregateFunctionFactory = Rc<Fn() -> Box<AggregateFunction>>;
+
+/// Registry of user-defined aggregate functions, keyed by name. `ExecutionContext`
+/// owns one of these; expression compilation consults it whenever it encounters an
+/// aggregate function name that is not one of the built-ins handled by `AggregateType`,
+/// and `create_aggregate_entry` consults it again to instantiate the accumulator
+#[derive(Clone, Default)]
+pub struct AggregateRegistry {
+    udafs: FnvHashMap<String, (AggregateFunctionFactory, DataType)>,
+}
+
+impl AggregateRegistry {
+    pub fn new() -> Self {
+        Self { udafs: FnvHashMap::default() }
+    }
+
+    /// Register a UDAF under `name` so it can later be resolved by name during
+    /// expression compilation and instantiated during aggregate execution
+    pub fn register(&mut self, name: &str, return_type: DataType, factory: AggregateFunctionFactory) {
+        self.udafs.insert(name.to_string(), (factory, return_type));
+    }
+
+    /// The declared return type of a registered UDAF, used by expression compilation
+    pub fn return_type(&self, name: &str) -> Option<&DataType> {
+        self.udafs.get(name).map(|(_, return_type)| return_type)
+    }
+
+    fn create(&self, name: &str) -> Result<Box<AggregateFunction>> {
+        match self.udafs.get(name) {
+            Some((factory, _)) => Ok(factory()),
+            None => Err(ExecutionError::General(format!("No UDAF registered with name '{}'", name))),
+        }
+    }
+}
 
 pub struct AggregateRelation {
     schema: Arc<Schema>,
     input: Rc<RefCell<Relation>>,
     group_expr: Vec<RuntimeExpr>,
     aggr_expr: Vec<RuntimeExpr>,
+    registry: Rc<AggregateRegistry>,
+    /// Set once the whole input has been consumed and the single aggregated batch has
+    /// been emitted, so a second call to `next()` correctly signals end-of-stream
+    /// instead of re-aggregating
+    done: bool,
 }
 
 
@@ -45,17 +90,44 @@ impl AggregateRelation {
         input: Rc<RefCell<Relation>>,
         group_expr: Vec<RuntimeExpr>,
         aggr_expr: Vec<RuntimeExpr>,
+        registry: Rc<AggregateRegistry>,
     ) -> Self {
         AggregateRelation {
             schema,
             input,
             group_expr,
             aggr_expr,
+            registry,
+            done: false,
         }
     }
+
+    /// The input relation being aggregated, exposed so plan producers can recurse
+    /// into it
+    pub(crate) fn input(&self) -> &Rc<RefCell<Relation>> {
+        &self.input
+    }
+
+    /// The GROUP BY expressions, empty for a whole-input aggregate
+    pub(crate) fn group_expr(&self) -> &[RuntimeExpr] {
+        &self.group_expr
+    }
+
+    /// The aggregate measures, in output order (after the GROUP BY columns)
+    pub(crate) fn aggr_expr(&self) -> &[RuntimeExpr] {
+        &self.aggr_expr
+    }
+
+    /// The UDAF registry this relation was built with, needed to reconstruct it
+    pub(crate) fn registry(&self) -> &Rc<AggregateRegistry> {
+        &self.registry
+    }
 }
 
 /// Enumeration of types that can be used in a GROUP BY expression
+///
+/// Floating point values are stored as their bit pattern so that the enum can derive
+/// `Eq`/`Hash` and participate as a `HashMap` key
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 enum GroupByScalar {
     Boolean(bool),
@@ -67,15 +139,93 @@ enum GroupByScalar {
     Int16(i16),
     Int32(i32),
     Int64(i64),
+    Float32(u32),
+    Float64(u64),
     Utf8(String),
 }
 
 trait AggregateFunction {
     fn accumulate(&mut self, value: &Option<ScalarValue>);
-    fn result(&self) -> &Option<ScalarValue>;
+    fn result(&mut self) -> &Option<ScalarValue>;
     fn data_type(&self) -> &DataType;
 }
 
+/// Body of one match arm per Arrow primitive type: downcasts `$ARRAY` to `$ARRAY_TY`
+/// and extracts the value at `$ROW` into a scalar built with `$BUILD`
+/// (e.g. `ScalarValue::Int32` or `GroupByScalar::Int32`)
+macro_rules! downcast_and_extract {
+    ($ARRAY:expr, $ROW:expr, $ARRAY_TY:ty, $BUILD:path) => {{
+        let array = $ARRAY.as_any().downcast_ref::<$ARRAY_TY>().unwrap();
+        $BUILD(array.value($ROW))
+    }};
+}
+
+/// Expands a binary operator over every pair of numeric `ScalarValue` variants of the
+/// same type, used by `compare_scalars` so every numeric type is handled
+/// without repeating the same match arm for each one
+macro_rules! numeric_scalar_op {
+    ($a:expr, $b:expr, |$x:ident, $y:ident| $op:expr) => {
+        match ($a, $b) {
+            (ScalarValue::Int8($x), ScalarValue::Int8($y)) => $op,
+            (ScalarValue::Int16($x), ScalarValue::Int16($y)) => $op,
+            (ScalarValue::Int32($x), ScalarValue::Int32($y)) => $op,
+            (ScalarValue::Int64($x), ScalarValue::Int64($y)) => $op,
+            (ScalarValue::UInt8($x), ScalarValue::UInt8($y)) => $op,
+            (ScalarValue::UInt16($x), ScalarValue::UInt16($y)) => $op,
+            (ScalarValue::UInt32($x), ScalarValue::UInt32($y)) => $op,
+            (ScalarValue::UInt64($x), ScalarValue::UInt64($y)) => $op,
+            (ScalarValue::Float32($x), ScalarValue::Float32($y)) => $op,
+            (ScalarValue::Float64($x), ScalarValue::Float64($y)) => $op,
+            _ => panic!("Cannot combine scalar values of different or non-numeric types"),
+        }
+    };
+}
+
+/// Compare two scalar values of the same type, used by MIN/MAX
+fn compare_scalars(a: &ScalarValue, b: &ScalarValue) -> ::std::cmp::Ordering {
+    match (a, b) {
+        (ScalarValue::Utf8(a), ScalarValue::Utf8(b)) => a.cmp(b),
+        (ScalarValue::Boolean(a), ScalarValue::Boolean(b)) => a.cmp(b),
+        _ => numeric_scalar_op!(a, b, |a, b| a
+            .partial_cmp(b)
+            .unwrap_or(::std::cmp::Ordering::Equal)),
+    }
+}
+
+/// Convert a scalar value to f64, used by AVG and PERCENTILE_CONT
+fn scalar_to_f64(value: &ScalarValue) -> f64 {
+    match value {
+        ScalarValue::Int8(n) => *n as f64,
+        ScalarValue::Int16(n) => *n as f64,
+        ScalarValue::Int32(n) => *n as f64,
+        ScalarValue::Int64(n) => *n as f64,
+        ScalarValue::UInt8(n) => *n as f64,
+        ScalarValue::UInt16(n) => *n as f64,
+        ScalarValue::UInt32(n) => *n as f64,
+        ScalarValue::UInt64(n) => *n as f64,
+        ScalarValue::Float32(n) => *n as f64,
+        ScalarValue::Float64(n) => *n,
+        _ => panic!("Unsupported data type for numeric aggregation"),
+    }
+}
+
+/// Add two scalar values of the same type together, used by SUM
+fn add_scalars(a: &ScalarValue, b: &ScalarValue) -> ScalarValue {
+    match (a, b) {
+        (ScalarValue::Int8(a), ScalarValue::Int8(b)) => ScalarValue::Int8(a + b),
+        (ScalarValue::Int16(a), ScalarValue::Int16(b)) => ScalarValue::Int16(a + b),
+        (ScalarValue::Int32(a), ScalarValue::Int32(b)) => ScalarValue::Int32(a + b),
+        (ScalarValue::Int64(a), ScalarValue::Int64(b)) => ScalarValue::Int64(a + b),
+        (ScalarValue::UInt8(a), ScalarValue::UInt8(b)) => ScalarValue::UInt8(a + b),
+        (ScalarValue::UInt16(a), ScalarValue::UInt16(b)) => ScalarValue::UInt16(a + b),
+        (ScalarValue::UInt32(a), ScalarValue::UInt32(b)) => ScalarValue::UInt32(a + b),
+        (ScalarValue::UInt64(a), ScalarValue::UInt64(b)) => ScalarValue::UInt64(a + b),
+        (ScalarValue::Float32(a), ScalarValue::Float32(b)) => ScalarValue::Float32(a + b),
+        (ScalarValue::Float64(a), ScalarValue::Float64(b)) => ScalarValue::Float64(a + b),
+        _ => panic!("Cannot add scalar values of different types"),
+    }
+}
+
 struct MinFunction {
     data_type: DataType,
     value: Option<ScalarValue>,
@@ -89,9 +239,15 @@ impl MinFunction {
 
 impl AggregateFunction for MinFunction {
     fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.value = Some(match &self.value {
+                Some(current) if compare_scalars(current, value) == ::std::cmp::Ordering::Less => current.clone(),
+                _ => value.clone(),
+            });
+        }
     }
 
-    fn result(&self) -> &Option<ScalarValue> {
+    fn result(&mut self) -> &Option<ScalarValue> {
         &self.value
     }
 
@@ -100,6 +256,336 @@ impl AggregateFunction for MinFunction {
     }
 }
 
+struct MaxFunction {
+    data_type: DataType,
+    value: Option<ScalarValue>,
+}
+
+impl MaxFunction {
+    fn new(data_type: &DataType) -> Self {
+        Self { data_type: data_type.clone(), value: None }
+    }
+}
+
+impl AggregateFunction for MaxFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.value = Some(match &self.value {
+                Some(current) if compare_scalars(current, value) == ::std::cmp::Ordering::Greater => current.clone(),
+                _ => value.clone(),
+            });
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        &self.value
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+struct SumFunction {
+    data_type: DataType,
+    value: Option<ScalarValue>,
+}
+
+impl SumFunction {
+    fn new(data_type: &DataType) -> Self {
+        Self { data_type: data_type.clone(), value: None }
+    }
+}
+
+impl AggregateFunction for SumFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.value = Some(match &self.value {
+                Some(current) => add_scalars(current, value),
+                None => value.clone(),
+            });
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        &self.value
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+struct CountFunction {
+    data_type: DataType,
+    count: i64,
+    value: Option<ScalarValue>,
+}
+
+impl CountFunction {
+    fn new() -> Self {
+        // a group with zero non-null rows is still a real group and COUNT(...) over it
+        // is 0, not NULL, so the running count starts as a value rather than `None`
+        Self { data_type: DataType::Int64, count: 0, value: Some(ScalarValue::Int64(0)) }
+    }
+}
+
+impl AggregateFunction for CountFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if value.is_some() {
+            self.count += 1;
+            self.value = Some(ScalarValue::Int64(self.count));
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        &self.value
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+struct AvgFunction {
+    data_type: DataType,
+    sum: f64,
+    count: i64,
+    value: Option<ScalarValue>,
+}
+
+impl AvgFunction {
+    fn new(data_type: &DataType) -> Self {
+        Self { data_type: data_type.clone(), sum: 0.0, count: 0, value: None }
+    }
+}
+
+impl AggregateFunction for AvgFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.sum += scalar_to_f64(value);
+            self.count += 1;
+            self.value = Some(ScalarValue::Float64(self.sum / self.count as f64));
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        &self.value
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Ordered-set aggregate that needs the full set of values before it can produce a
+/// result, so `accumulate` just buffers and the real work happens lazily in `result`
+struct PercentileContFunction {
+    data_type: DataType,
+    p: f64,
+    values: Vec<ScalarValue>,
+    result: Option<ScalarValue>,
+}
+
+impl PercentileContFunction {
+    fn new(data_type: &DataType, p: f64) -> Self {
+        Self { data_type: data_type.clone(), p, values: Vec::new(), result: None }
+    }
+}
+
+impl AggregateFunction for PercentileContFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.values.push(value.clone());
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        if self.result.is_none() && !self.values.is_empty() {
+            self.values.sort_by(compare_scalars);
+            let n = self.values.len();
+            let rank = self.p * (n - 1) as f64;
+            let lo = rank.floor() as usize;
+            let hi = rank.ceil() as usize;
+            let lo_value = scalar_to_f64(&self.values[lo]);
+            let result = if lo == hi {
+                lo_value
+            } else {
+                let hi_value = scalar_to_f64(&self.values[hi]);
+                lo_value + (hi_value - lo_value) * (rank - lo as f64)
+            };
+            self.result = Some(ScalarValue::Float64(result));
+        }
+        &self.result
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// `PERCENTILE_DISC` returns the smallest buffered value whose cumulative fraction is
+/// at least `p`, rather than interpolating like `PERCENTILE_CONT`
+struct PercentileDiscFunction {
+    data_type: DataType,
+    p: f64,
+    values: Vec<ScalarValue>,
+    result: Option<ScalarValue>,
+}
+
+impl PercentileDiscFunction {
+    fn new(data_type: &DataType, p: f64) -> Self {
+        Self { data_type: data_type.clone(), p, values: Vec::new(), result: None }
+    }
+}
+
+impl AggregateFunction for PercentileDiscFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.values.push(value.clone());
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        if self.result.is_none() && !self.values.is_empty() {
+            self.values.sort_by(compare_scalars);
+            let n = self.values.len();
+            let index = if self.p == 0.0 {
+                0
+            } else {
+                ((self.p * n as f64).ceil() as usize).saturating_sub(1).min(n - 1)
+            };
+            self.result = Some(self.values[index].clone());
+        }
+        &self.result
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// `MODE` returns the most frequently occurring buffered value, breaking ties in
+/// favour of the smallest value
+struct ModeFunction {
+    data_type: DataType,
+    values: Vec<ScalarValue>,
+    result: Option<ScalarValue>,
+}
+
+impl ModeFunction {
+    fn new(data_type: &DataType) -> Self {
+        Self { data_type: data_type.clone(), values: Vec::new(), result: None }
+    }
+}
+
+impl AggregateFunction for ModeFunction {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            self.values.push(value.clone());
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        if self.result.is_none() && !self.values.is_empty() {
+            self.values.sort_by(compare_scalars);
+            let mut best: Option<(ScalarValue, usize)> = None;
+            let mut i = 0;
+            while i < self.values.len() {
+                let mut j = i + 1;
+                while j < self.values.len()
+                    && compare_scalars(&self.values[i], &self.values[j]) == ::std::cmp::Ordering::Equal
+                {
+                    j += 1;
+                }
+                let count = j - i;
+                let is_better = match &best {
+                    Some((_, best_count)) => count > *best_count,
+                    None => true,
+                };
+                if is_better {
+                    best = Some((self.values[i].clone(), count));
+                }
+                i = j;
+            }
+            self.result = best.map(|(value, _)| value);
+        }
+        &self.result
+    }
+
+    fn data_type(&self) -> &DataType {
+        &self.data_type
+    }
+}
+
+/// Forward the trait through a `Box`, so a boxed accumulator can be nested inside
+/// another accumulator (e.g. `DistinctAccumulator`) without unwrapping it first
+impl AggregateFunction for Box<AggregateFunction> {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        (**self).accumulate(value)
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        (**self).result()
+    }
+
+    fn data_type(&self) -> &DataType {
+        (**self).data_type()
+    }
+}
+
+/// Canonicalize a `ScalarValue` into a hashable key, mirroring `GroupByScalar`'s use
+/// of bit patterns for floats, so it can be used as an `FnvHashSet` entry to track
+/// which values a `DISTINCT` aggregate has already seen
+fn scalar_value_key(value: &ScalarValue) -> GroupByScalar {
+    match value {
+        ScalarValue::Boolean(v) => GroupByScalar::Boolean(*v),
+        ScalarValue::Int8(v) => GroupByScalar::Int8(*v),
+        ScalarValue::Int16(v) => GroupByScalar::Int16(*v),
+        ScalarValue::Int32(v) => GroupByScalar::Int32(*v),
+        ScalarValue::Int64(v) => GroupByScalar::Int64(*v),
+        ScalarValue::UInt8(v) => GroupByScalar::UInt8(*v),
+        ScalarValue::UInt16(v) => GroupByScalar::UInt16(*v),
+        ScalarValue::UInt32(v) => GroupByScalar::UInt32(*v),
+        ScalarValue::UInt64(v) => GroupByScalar::UInt64(*v),
+        ScalarValue::Float32(v) => GroupByScalar::Float32(v.to_bits()),
+        ScalarValue::Float64(v) => GroupByScalar::Float64(v.to_bits()),
+        ScalarValue::Utf8(v) => GroupByScalar::Utf8(v.clone()),
+    }
+}
+
+/// Wraps another accumulator so that repeated values are only forwarded to it once,
+/// implementing `COUNT(DISTINCT ...)`, `SUM(DISTINCT ...)`, etc. on top of the same
+/// accumulators used for the non-distinct case
+struct DistinctAccumulator {
+    inner: Box<AggregateFunction>,
+    seen: FnvHashSet<GroupByScalar>,
+}
+
+impl DistinctAccumulator {
+    fn new(inner: Box<AggregateFunction>) -> Self {
+        Self { inner, seen: FnvHashSet::default() }
+    }
+}
+
+impl AggregateFunction for DistinctAccumulator {
+    fn accumulate(&mut self, value: &Option<ScalarValue>) {
+        if let Some(value) = value {
+            if self.seen.insert(scalar_value_key(value)) {
+                self.inner.accumulate(&Some(value.clone()));
+            }
+        }
+    }
+
+    fn result(&mut self) -> &Option<ScalarValue> {
+        self.inner.result()
+    }
+
+    fn data_type(&self) -> &DataType {
+        self.inner.data_type()
+    }
+}
+
 struct AggregateEntry {
     aggr_values: Vec<Rc<RefCell<AggregateFunction>>>
 }
@@ -112,57 +598,186 @@ impl AggregateEntry {
 }
 
 /// Create an initial aggregate entry
-fn create_aggregate_entry(aggr_expr: &Vec<RuntimeExpr>) -> Rc<RefCell<AggregateEntry>> {
+fn create_aggregate_entry(aggr_expr: &Vec<RuntimeExpr>, registry: &AggregateRegistry) -> Result<Rc<RefCell<AggregateEntry>>> {
     //println!("Creating new aggregate entry");
 
     let functions = aggr_expr
         .iter()
         .map(|e| match e {
-            RuntimeExpr::AggregateFunction { ref f, ref t, .. } => match f {
-                AggregateType::Min => Rc::new(RefCell::new(MinFunction::new(t))) as Rc<RefCell<AggregateFunction>>,
-//                AggregateType::Max => Box::new(MaxFunction::new(t)) as Box<AggregateFunction>,
-//                AggregateType::Count => Box::new(CountFunction::new()) as Box<AggregateFunction>,
-//                AggregateType::Sum => Box::new(SumFunction::new(t)) as Box<AggregateFunction>,
-                _ => panic!(),
-            },
-            _ => panic!(),
+            RuntimeExpr::AggregateFunction { ref f, ref t, ref distinct, .. } => {
+                let inner: Box<AggregateFunction> = match f {
+                    AggregateType::Min => Box::new(MinFunction::new(t)),
+                    AggregateType::Max => Box::new(MaxFunction::new(t)),
+                    AggregateType::Sum => Box::new(SumFunction::new(t)),
+                    AggregateType::Count => Box::new(CountFunction::new()),
+                    AggregateType::Avg => Box::new(AvgFunction::new(t)),
+                    AggregateType::PercentileCont(p) => Box::new(PercentileContFunction::new(t, *p)),
+                    AggregateType::PercentileDisc(p) => Box::new(PercentileDiscFunction::new(t, *p)),
+                    AggregateType::Mode => Box::new(ModeFunction::new(t)),
+                    AggregateType::Udaf(name) => registry.create(name)?,
+                };
+
+                Ok(if *distinct {
+                    Rc::new(RefCell::new(DistinctAccumulator::new(inner))) as Rc<RefCell<AggregateFunction>>
+                } else {
+                    Rc::new(RefCell::new(inner)) as Rc<RefCell<AggregateFunction>>
+                })
+            }
+            _ => Err(ExecutionError::General("Invalid aggregate expression".to_string())),
         })
-        .collect();
+        .collect::<Result<Vec<_>>>()?;
 
-    Rc::new(RefCell::new(AggregateEntry {
+    Ok(Rc::new(RefCell::new(AggregateEntry {
         aggr_values: functions,
-    }))
+    })))
 }
 
-//TODO macros to make this code less verbose
+/// Body of one match arm per Arrow primitive type: unwraps every `GroupByScalar` (or
+/// `Option<ScalarValue>`) in `$VALUES` back into its native Rust type and rebuilds a
+/// typed Arrow array from the resulting `Vec`
+macro_rules! typed_array_from_scalars {
+    ($ARRAY_TY:ty, $VALUES:expr, |$v:ident| $extract:expr) => {
+        Arc::new(<$ARRAY_TY>::from(
+            $VALUES.into_iter().map(|$v| $extract).collect::<Vec<_>>(),
+        )) as ArrayRef
+    };
+}
 
-fn array_min(array: ArrayRef, dt: &DataType) -> Result<ArrayRef> {
+/// Build an array from the group-by keys collected for a single group-by column
+fn group_by_scalar_array(dt: &DataType, values: Vec<GroupByScalar>) -> Result<ArrayRef> {
     match dt {
-        DataType::Int32 => {
-            let value = array_ops::min(array.as_any().downcast_ref::<Int32Array>().unwrap());
-            Ok(Arc::new(Int32Array::from(vec![value])) as ArrayRef)
-        }
-        DataType::Float64 => {
-            let value = array_ops::min(array.as_any().downcast_ref::<Float64Array>().unwrap());
-            Ok(Arc::new(Float64Array::from(vec![value])) as ArrayRef)
-        }
-        //TODO support all types
-        _ => Err(ExecutionError::NotImplemented("Unsupported data type for MIN".to_string()))
+        DataType::Boolean => Ok(typed_array_from_scalars!(BooleanArray, values, |v| match v {
+            GroupByScalar::Boolean(n) => n,
+            _ => panic!("Invalid GroupByScalar for Boolean column"),
+        })),
+        DataType::Int8 => Ok(typed_array_from_scalars!(Int8Array, values, |v| match v {
+            GroupByScalar::Int8(n) => n,
+            _ => panic!("Invalid GroupByScalar for Int8 column"),
+        })),
+        DataType::Int16 => Ok(typed_array_from_scalars!(Int16Array, values, |v| match v {
+            GroupByScalar::Int16(n) => n,
+            _ => panic!("Invalid GroupByScalar for Int16 column"),
+        })),
+        DataType::Int32 => Ok(typed_array_from_scalars!(Int32Array, values, |v| match v {
+            GroupByScalar::Int32(n) => n,
+            _ => panic!("Invalid GroupByScalar for Int32 column"),
+        })),
+        DataType::Int64 => Ok(typed_array_from_scalars!(Int64Array, values, |v| match v {
+            GroupByScalar::Int64(n) => n,
+            _ => panic!("Invalid GroupByScalar for Int64 column"),
+        })),
+        DataType::UInt8 => Ok(typed_array_from_scalars!(UInt8Array, values, |v| match v {
+            GroupByScalar::UInt8(n) => n,
+            _ => panic!("Invalid GroupByScalar for UInt8 column"),
+        })),
+        DataType::UInt16 => Ok(typed_array_from_scalars!(UInt16Array, values, |v| match v {
+            GroupByScalar::UInt16(n) => n,
+            _ => panic!("Invalid GroupByScalar for UInt16 column"),
+        })),
+        DataType::UInt32 => Ok(typed_array_from_scalars!(UInt32Array, values, |v| match v {
+            GroupByScalar::UInt32(n) => n,
+            _ => panic!("Invalid GroupByScalar for UInt32 column"),
+        })),
+        DataType::UInt64 => Ok(typed_array_from_scalars!(UInt64Array, values, |v| match v {
+            GroupByScalar::UInt64(n) => n,
+            _ => panic!("Invalid GroupByScalar for UInt64 column"),
+        })),
+        DataType::Float32 => Ok(typed_array_from_scalars!(Float32Array, values, |v| match v {
+            GroupByScalar::Float32(bits) => f32::from_bits(bits),
+            _ => panic!("Invalid GroupByScalar for Float32 column"),
+        })),
+        DataType::Float64 => Ok(typed_array_from_scalars!(Float64Array, values, |v| match v {
+            GroupByScalar::Float64(bits) => f64::from_bits(bits),
+            _ => panic!("Invalid GroupByScalar for Float64 column"),
+        })),
+        DataType::Utf8 => Ok(Arc::new(BinaryArray::from(
+            values.into_iter().map(|v| match v {
+                GroupByScalar::Utf8(s) => s,
+                _ => panic!("Invalid GroupByScalar for Utf8 column"),
+            }).collect::<Vec<String>>(),
+        )) as ArrayRef),
+        _ => Err(ExecutionError::NotImplemented("Unsupported data type for GROUP BY column".to_string())),
     }
 }
 
-fn array_max(array: ArrayRef, dt: &DataType) -> Result<ArrayRef> {
+/// Body of one match arm per Arrow primitive type: unwraps every per-group
+/// aggregate result into `Option<nativeType>`, preserving a `None` result (e.g. MIN
+/// over an all-NULL group) as a null array slot instead of panicking, and rebuilds a
+/// typed Arrow array from the resulting `Vec`
+macro_rules! typed_array_from_scalar_options {
+    ($ARRAY_TY:ty, $VALUES:expr, |$v:ident| $extract:expr) => {
+        Arc::new(<$ARRAY_TY>::from(
+            $VALUES.into_iter().map(|$v| $extract).collect::<Vec<_>>(),
+        )) as ArrayRef
+    };
+}
+
+/// Build an array from the per-group aggregate results for a single aggregate column
+fn scalar_values_to_array(dt: &DataType, values: Vec<Option<ScalarValue>>) -> Result<ArrayRef> {
     match dt {
-        DataType::Int32 => {
-            let value = array_ops::max(array.as_any().downcast_ref::<Int32Array>().unwrap());
-            Ok(Arc::new(Int32Array::from(vec![value])) as ArrayRef)
-        }
-        DataType::Float64 => {
-            let value = array_ops::max(array.as_any().downcast_ref::<Float64Array>().unwrap());
-            Ok(Arc::new(Float64Array::from(vec![value])) as ArrayRef)
-        }
-        //TODO support all types
-        _ => Err(ExecutionError::NotImplemented("Unsupported data type for MAX".to_string()))
+        DataType::Boolean => Ok(typed_array_from_scalar_options!(BooleanArray, values, |v| match v {
+            Some(ScalarValue::Boolean(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Boolean column"),
+        })),
+        DataType::Int8 => Ok(typed_array_from_scalar_options!(Int8Array, values, |v| match v {
+            Some(ScalarValue::Int8(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Int8 column"),
+        })),
+        DataType::Int16 => Ok(typed_array_from_scalar_options!(Int16Array, values, |v| match v {
+            Some(ScalarValue::Int16(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Int16 column"),
+        })),
+        DataType::Int32 => Ok(typed_array_from_scalar_options!(Int32Array, values, |v| match v {
+            Some(ScalarValue::Int32(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Int32 column"),
+        })),
+        DataType::Int64 => Ok(typed_array_from_scalar_options!(Int64Array, values, |v| match v {
+            Some(ScalarValue::Int64(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Int64 column"),
+        })),
+        DataType::UInt8 => Ok(typed_array_from_scalar_options!(UInt8Array, values, |v| match v {
+            Some(ScalarValue::UInt8(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for UInt8 column"),
+        })),
+        DataType::UInt16 => Ok(typed_array_from_scalar_options!(UInt16Array, values, |v| match v {
+            Some(ScalarValue::UInt16(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for UInt16 column"),
+        })),
+        DataType::UInt32 => Ok(typed_array_from_scalar_options!(UInt32Array, values, |v| match v {
+            Some(ScalarValue::UInt32(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for UInt32 column"),
+        })),
+        DataType::UInt64 => Ok(typed_array_from_scalar_options!(UInt64Array, values, |v| match v {
+            Some(ScalarValue::UInt64(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for UInt64 column"),
+        })),
+        DataType::Float32 => Ok(typed_array_from_scalar_options!(Float32Array, values, |v| match v {
+            Some(ScalarValue::Float32(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Float32 column"),
+        })),
+        DataType::Float64 => Ok(typed_array_from_scalar_options!(Float64Array, values, |v| match v {
+            Some(ScalarValue::Float64(n)) => Some(n),
+            None => None,
+            _ => panic!("Invalid aggregate result for Float64 column"),
+        })),
+        DataType::Utf8 => Ok(Arc::new(BinaryArray::from(
+            values.into_iter().map(|v| match v {
+                Some(ScalarValue::Utf8(s)) => Some(s),
+                None => None,
+                _ => panic!("Invalid aggregate result for Utf8 column"),
+            }).collect::<Vec<Option<String>>>(),
+        )) as ArrayRef),
+        _ => Err(ExecutionError::NotImplemented("Unsupported data type for aggregate column".to_string())),
     }
 }
 
@@ -175,16 +790,29 @@ fn update_accumulators(batch: &RecordBatch, row: usize, accumulator_set: &mut Ag
                 // evaluate argument to aggregate function
                 match args[0](&batch) {
                     Ok(array) => {
-                        let value: Option<ScalarValue> = match t {
-                            DataType::Int32 => {
-                                let z = array.as_any().downcast_ref::<Int32Array>().unwrap();
-                                Some(ScalarValue::Int32(z.value(row)))
-                            }
-                            DataType::Float64 => {
-                                let z = array.as_any().downcast_ref::<Float64Array>().unwrap();
-                                Some(ScalarValue::Float64(z.value(row)))
-                            }
-                            _ => panic!()
+                        // a NULL slot must fold into `None` so accumulators can skip it,
+                        // e.g. COUNT must not count it and SUM/AVG must not add garbage
+                        let value: Option<ScalarValue> = if array.is_null(row) {
+                            None
+                        } else {
+                            Some(match t {
+                                DataType::Boolean => downcast_and_extract!(array, row, BooleanArray, ScalarValue::Boolean),
+                                DataType::Int8 => downcast_and_extract!(array, row, Int8Array, ScalarValue::Int8),
+                                DataType::Int16 => downcast_and_extract!(array, row, Int16Array, ScalarValue::Int16),
+                                DataType::Int32 => downcast_and_extract!(array, row, Int32Array, ScalarValue::Int32),
+                                DataType::Int64 => downcast_and_extract!(array, row, Int64Array, ScalarValue::Int64),
+                                DataType::UInt8 => downcast_and_extract!(array, row, UInt8Array, ScalarValue::UInt8),
+                                DataType::UInt16 => downcast_and_extract!(array, row, UInt16Array, ScalarValue::UInt16),
+                                DataType::UInt32 => downcast_and_extract!(array, row, UInt32Array, ScalarValue::UInt32),
+                                DataType::UInt64 => downcast_and_extract!(array, row, UInt64Array, ScalarValue::UInt64),
+                                DataType::Float32 => downcast_and_extract!(array, row, Float32Array, ScalarValue::Float32),
+                                DataType::Float64 => downcast_and_extract!(array, row, Float64Array, ScalarValue::Float64),
+                                DataType::Utf8 => {
+                                    let z = array.as_any().downcast_ref::<BinaryArray>().unwrap();
+                                    ScalarValue::Utf8(String::from(str::from_utf8(z.get_value(row)).unwrap()))
+                                }
+                                _ => panic!("Unsupported data type for aggregate argument")
+                            })
                         };
                         accumulator_set.accumulate(j, value);
                     }
@@ -199,108 +827,126 @@ fn update_accumulators(batch: &RecordBatch, row: usize, accumulator_set: &mut Ag
 
 impl Relation for AggregateRelation {
     fn next(&mut self) -> Result<Option<RecordBatch>> {
-        match self.input.borrow_mut().next()? {
-            Some(batch) => {
-
-                if self.group_expr.is_empty() {
-
-                    // perform simple aggregate on entire columns without grouping logic
-                    let columns: Result<Vec<ArrayRef>> = self.aggr_expr.iter().map(|expr| match expr {
-                        RuntimeExpr::AggregateFunction { f, args, t, .. } => {
-
-                            // evaluate argument to aggregate function
-                            match args[0](&batch) {
-                                Ok(array) => match f {
-                                    AggregateType::Min => array_min(array, &t),
-                                    AggregateType::Max => array_max(array, &t),
-                                    _ => Err(ExecutionError::NotImplemented("Unsupported aggregate function".to_string()))
-                                }
-                                Err(e) => Err(ExecutionError::ExecutionError("Failed to evaluate argument to aggregate function".to_string()))
-                            }
-
-                        },
-                        _ => Err(ExecutionError::General("Invalid aggregate expression".to_string()))
-
-                    }).collect();
-
-                    Ok(Some(RecordBatch::new(self.schema.clone(), columns?)))
-
-                } else {
-                    let mut map: FnvHashMap<Vec<GroupByScalar>, Rc<RefCell<AggregateEntry>>> =
-                        FnvHashMap::default();
-
-                    // evaulate the group by expressions on this batch
-                    let group_by_keys: Vec<ArrayRef> =
-                        self.group_expr.iter()
-                            .map(|e| e.get_func()(&batch))
-                            .collect::<Result<Vec<ArrayRef>>>()?;
+        if self.done {
+            return Ok(None);
+        }
 
+        // An aggregate consumes its entire input before it can emit anything, since a
+        // group (or, for an ungrouped aggregate, the one implicit group) can have rows
+        // spread across more than one input batch. So this loop drains `self.input`
+        // completely, then a single result batch is built and `self.done` is set so a
+        // later call to `next()` correctly reports end-of-stream rather than
+        // re-aggregating an already-exhausted input.
+        let mut map: FnvHashMap<Vec<GroupByScalar>, Rc<RefCell<AggregateEntry>>> =
+            FnvHashMap::default();
 
-                    // iterate over each row in the batch
-                    for row in 0..batch.num_rows() {
+        while let Some(batch) = self.input.borrow_mut().next()? {
+            // evaluate the group by expressions on this batch; an ungrouped aggregate
+            // has no group_expr, so every row in every batch shares the same empty key
+            let group_by_keys: Vec<ArrayRef> =
+                self.group_expr.iter()
+                    .map(|e| e.get_func()(&batch))
+                    .collect::<Result<Vec<ArrayRef>>>()?;
 
-                        //NOTE: this seems pretty inefficient, performing a match and a downcast on each row
+            // iterate over each row in the batch
+            for row in 0..batch.num_rows() {
 
-                        // create key
-                        let key: Vec<GroupByScalar> = group_by_keys.iter().map(|col| {
-                            //TODO: use macro to make this less verbose
-                            match col.data_type() {
-                                DataType::Int32 => {
-                                    let array = col.as_any().downcast_ref::<Int32Array>().unwrap();
-                                    GroupByScalar::Int32(array.value(row))
-                                }
-                                DataType::Utf8 => {
-                                    let array = col.as_any().downcast_ref::<BinaryArray>().unwrap();
-                                    GroupByScalar::Utf8(String::from(str::from_utf8(array.get_value(row)).unwrap()))
-                                }
-                                //TODO add all types
-                                _ => unimplemented!()
-                            }
-                        }).collect();
-
-                        //TODO: find more elegant way to write this instead of hacking around ownership issues
-
-                        let updated = match map.get(&key) {
-                            Some(entry) => {
-                                let mut accumulator_set = entry.borrow_mut();
-                                update_accumulators(&batch, row, &mut accumulator_set, &self.aggr_expr);
-                                true
-                            }
-                            None => false
-                        };
+                //NOTE: this seems pretty inefficient, performing a match and a downcast on each row
 
-                        if !updated {
-                            let accumulator_set = create_aggregate_entry(&self.aggr_expr);
-                            {
-                                let mut entry_mut = accumulator_set.borrow_mut();
-                                update_accumulators(&batch, row, &mut entry_mut, &self.aggr_expr);
-                            }
-                            map.insert(key.clone(), accumulator_set);
+                // create key
+                let key: Vec<GroupByScalar> = group_by_keys.iter().map(|col| {
+                    match col.data_type() {
+                        DataType::Boolean => downcast_and_extract!(col, row, BooleanArray, GroupByScalar::Boolean),
+                        DataType::Int8 => downcast_and_extract!(col, row, Int8Array, GroupByScalar::Int8),
+                        DataType::Int16 => downcast_and_extract!(col, row, Int16Array, GroupByScalar::Int16),
+                        DataType::Int32 => downcast_and_extract!(col, row, Int32Array, GroupByScalar::Int32),
+                        DataType::Int64 => downcast_and_extract!(col, row, Int64Array, GroupByScalar::Int64),
+                        DataType::UInt8 => downcast_and_extract!(col, row, UInt8Array, GroupByScalar::UInt8),
+                        DataType::UInt16 => downcast_and_extract!(col, row, UInt16Array, GroupByScalar::UInt16),
+                        DataType::UInt32 => downcast_and_extract!(col, row, UInt32Array, GroupByScalar::UInt32),
+                        DataType::UInt64 => downcast_and_extract!(col, row, UInt64Array, GroupByScalar::UInt64),
+                        DataType::Float32 => {
+                            let array = col.as_any().downcast_ref::<Float32Array>().unwrap();
+                            GroupByScalar::Float32(array.value(row).to_bits())
                         }
+                        DataType::Float64 => {
+                            let array = col.as_any().downcast_ref::<Float64Array>().unwrap();
+                            GroupByScalar::Float64(array.value(row).to_bits())
+                        }
+                        DataType::Utf8 => {
+                            let array = col.as_any().downcast_ref::<BinaryArray>().unwrap();
+                            GroupByScalar::Utf8(String::from(str::from_utf8(array.get_value(row)).unwrap()))
+                        }
+                        //TODO add all types
+                        _ => unimplemented!("Unsupported data type for GROUP BY key")
                     }
+                }).collect();
 
-                    // create record batch from the accumulators
-                    let mut result_columns: Vec<ArrayRef> =
-                        Vec::with_capacity(self.group_expr.len() + self.aggr_expr.len());
+                //TODO: find more elegant way to write this instead of hacking around ownership issues
 
-                    for i in 0..group_by_keys.len() {
-                        result_columns.push(group_by_keys[i].clone());
+                let updated = match map.get(&key) {
+                    Some(entry) => {
+                        let mut accumulator_set = entry.borrow_mut();
+                        update_accumulators(&batch, row, &mut accumulator_set, &self.aggr_expr);
+                        true
                     }
+                    None => false
+                };
 
-                    //TODO build record batch from aggregate results
-                    for (k, v) in map.iter() {
-
+                if !updated {
+                    let accumulator_set = create_aggregate_entry(&self.aggr_expr, &self.registry)?;
+                    {
+                        let mut entry_mut = accumulator_set.borrow_mut();
+                        update_accumulators(&batch, row, &mut entry_mut, &self.aggr_expr);
                     }
+                    map.insert(key.clone(), accumulator_set);
+                }
+            }
+        }
 
-                    Ok(Some(RecordBatch::new(
-                        self.schema.clone(),
-                        result_columns
+        self.done = true;
 
-                    )))
-                }
+        if map.is_empty() {
+            return Ok(None);
+        }
+
+        // transpose the map of group key -> accumulators into one column per
+        // group-by expression plus one column per aggregate expression
+        let mut group_columns: Vec<Vec<GroupByScalar>> =
+            (0..self.group_expr.len()).map(|_| Vec::with_capacity(map.len())).collect();
+        let mut aggr_columns: Vec<Vec<Option<ScalarValue>>> =
+            (0..self.aggr_expr.len()).map(|_| Vec::with_capacity(map.len())).collect();
+
+        for (k, v) in map.iter() {
+            for i in 0..k.len() {
+                group_columns[i].push(k[i].clone());
+            }
+            let entry = v.borrow();
+            for i in 0..entry.aggr_values.len() {
+                aggr_columns[i].push(entry.aggr_values[i].borrow_mut().result().clone());
             }
-            None => Ok(None),
         }
+
+        // create record batch from the accumulators
+        let mut result_columns: Vec<ArrayRef> =
+            Vec::with_capacity(self.group_expr.len() + self.aggr_expr.len());
+
+        for (i, values) in group_columns.into_iter().enumerate() {
+            result_columns.push(group_by_scalar_array(&self.group_expr[i].get_type(), values)?);
+        }
+
+        for (i, values) in aggr_columns.into_iter().enumerate() {
+            let dt = match &self.aggr_expr[i] {
+                RuntimeExpr::AggregateFunction { t, .. } => t.clone(),
+                _ => return Err(ExecutionError::General("Invalid aggregate expression".to_string())),
+            };
+            result_columns.push(scalar_values_to_array(&dt, values)?);
+        }
+
+        Ok(Some(RecordBatch::new(
+            self.schema.clone(),
+            result_columns
+        )))
     }
 
     fn schema(&self) -> &Arc<Schema> {
@@ -337,7 +983,7 @@ mod tests {
             Field::new("min_lat", DataType::Float64, false),
         ]));
 
-        let mut projection = AggregateRelation::new(aggr_schema,relation, vec![], aggr_expr);
+        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], aggr_expr, Rc::new(AggregateRegistry::new()));
         let batch = projection.next().unwrap().unwrap();
         assert_eq!(1, batch.num_columns());
         let min_lat = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
@@ -361,7 +1007,7 @@ mod tests {
             Field::new("max_lat", DataType::Float64, false),
         ]));
 
-        let mut projection = AggregateRelation::new(aggr_schema,relation, vec![], aggr_expr);
+        let mut projection = AggregateRelation::new(aggr_schema, relation, vec![], aggr_expr, Rc::new(AggregateRegistry::new()));
         let batch = projection.next().unwrap().unwrap();
         assert_eq!(1, batch.num_columns());
         let max_lat = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
@@ -386,4 +1032,297 @@ mod tests {
         ))))
     }
 
+    /// Minimal in-memory `Relation` that yields a fixed sequence of batches, used to
+    /// exercise aggregation across more than one input batch without needing a CSV
+    /// fixture on disk
+    struct MemRelation {
+        schema: Arc<Schema>,
+        batches: Vec<Option<RecordBatch>>,
+    }
+
+    impl Relation for MemRelation {
+        fn next(&mut self) -> Result<Option<RecordBatch>> {
+            for slot in self.batches.iter_mut() {
+                if slot.is_some() {
+                    return Ok(slot.take());
+                }
+            }
+            Ok(None)
+        }
+
+        fn schema(&self) -> &Arc<Schema> {
+            &self.schema
+        }
+    }
+
+    #[test]
+    fn group_by_count_sum_avg() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("amount", DataType::Int32, false),
+        ]));
+        let context = ExecutionContext::new();
+
+        // split the category=1 rows across two batches to also verify that groups are
+        // accumulated across the whole input rather than per batch
+        let batch1 = RecordBatch::new(schema.clone(), vec![
+            Arc::new(Int32Array::from(vec![1, 1])) as ArrayRef,
+            Arc::new(Int32Array::from(vec![10, 20])) as ArrayRef,
+        ]);
+        let batch2 = RecordBatch::new(schema.clone(), vec![
+            Arc::new(Int32Array::from(vec![1, 2])) as ArrayRef,
+            Arc::new(Int32Array::from(vec![30, 5])) as ArrayRef,
+        ]);
+
+        let relation = Rc::new(RefCell::new(MemRelation {
+            schema: schema.clone(),
+            batches: vec![Some(batch1), Some(batch2)],
+        }));
+
+        let group_expr = vec![expression::compile_expr(&context, &Expr::Column(0), &schema).unwrap()];
+        let aggr_expr = vec![
+            expression::compile_expr(&context, &Expr::AggregateFunction {
+                name: String::from("count"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Int64,
+            }, &schema).unwrap(),
+            expression::compile_expr(&context, &Expr::AggregateFunction {
+                name: String::from("sum"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Int32,
+            }, &schema).unwrap(),
+            expression::compile_expr(&context, &Expr::AggregateFunction {
+                name: String::from("avg"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Float64,
+            }, &schema).unwrap(),
+        ];
+
+        let aggr_schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("count", DataType::Int64, false),
+            Field::new("sum", DataType::Int32, false),
+            Field::new("avg", DataType::Float64, false),
+        ]));
+
+        let mut aggregate = AggregateRelation::new(
+            aggr_schema,
+            relation,
+            group_expr,
+            aggr_expr,
+            Rc::new(AggregateRegistry::new()),
+        );
+
+        let batch = aggregate.next().unwrap().unwrap();
+        assert_eq!(2, batch.num_rows());
+        assert!(aggregate.next().unwrap().is_none());
+
+        let categories = batch.column(0).as_any().downcast_ref::<Int32Array>().unwrap();
+        let counts = batch.column(1).as_any().downcast_ref::<Int64Array>().unwrap();
+        let sums = batch.column(2).as_any().downcast_ref::<Int32Array>().unwrap();
+        let avgs = batch.column(3).as_any().downcast_ref::<Float64Array>().unwrap();
+
+        let mut rows: Vec<(i32, i64, i32, f64)> = (0..batch.num_rows())
+            .map(|i| (categories.value(i), counts.value(i), sums.value(i), avgs.value(i)))
+            .collect();
+        rows.sort_by_key(|r| r.0);
+
+        assert_eq!(vec![(1, 3, 60, 20.0), (2, 1, 5, 5.0)], rows);
+    }
+
+    #[test]
+    fn group_by_min_over_utf8_column() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("name", DataType::Utf8, false),
+        ]));
+        let context = ExecutionContext::new();
+
+        let batch = RecordBatch::new(schema.clone(), vec![
+            Arc::new(Int32Array::from(vec![1, 1, 2])) as ArrayRef,
+            Arc::new(BinaryArray::from(vec![
+                "banana".to_string(),
+                "apple".to_string(),
+                "cherry".to_string(),
+            ])) as ArrayRef,
+        ]);
+
+        let relation = Rc::new(RefCell::new(MemRelation {
+            schema: schema.clone(),
+            batches: vec![Some(batch)],
+        }));
+
+        let group_expr = vec![expression::compile_expr(&context, &Expr::Column(0), &schema).unwrap()];
+        let aggr_expr = vec![expression::compile_expr(&context, &Expr::AggregateFunction {
+            name: String::from("min"),
+            args: vec![Expr::Column(1)],
+            return_type: DataType::Utf8,
+        }, &schema).unwrap()];
+
+        let aggr_schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("min_name", DataType::Utf8, false),
+        ]));
+
+        let mut aggregate = AggregateRelation::new(
+            aggr_schema,
+            relation,
+            group_expr,
+            aggr_expr,
+            Rc::new(AggregateRegistry::new()),
+        );
+
+        let batch = aggregate.next().unwrap().unwrap();
+        assert_eq!(2, batch.num_rows());
+
+        let categories = batch.column(0).as_any().downcast_ref::<Int32Array>().unwrap();
+        let names = batch.column(1).as_any().downcast_ref::<BinaryArray>().unwrap();
+
+        let mut rows: Vec<(i32, String)> = (0..batch.num_rows())
+            .map(|i| (categories.value(i), String::from(str::from_utf8(names.get_value(i)).unwrap())))
+            .collect();
+        rows.sort_by_key(|r| r.0);
+
+        assert_eq!(
+            vec![(1, String::from("apple")), (2, String::from("cherry"))],
+            rows
+        );
+    }
+
+    #[test]
+    fn aggregate_over_all_null_group_is_null_not_a_panic() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("amount", DataType::Int32, true),
+        ]));
+        let context = ExecutionContext::new();
+
+        let batch = RecordBatch::new(schema.clone(), vec![
+            Arc::new(Int32Array::from(vec![1, 1])) as ArrayRef,
+            Arc::new(Int32Array::from(vec![None as Option<i32>, None])) as ArrayRef,
+        ]);
+
+        let relation = Rc::new(RefCell::new(MemRelation {
+            schema: schema.clone(),
+            batches: vec![Some(batch)],
+        }));
+
+        let group_expr = vec![expression::compile_expr(&context, &Expr::Column(0), &schema).unwrap()];
+        let aggr_expr = vec![
+            expression::compile_expr(&context, &Expr::AggregateFunction {
+                name: String::from("min"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Int32,
+            }, &schema).unwrap(),
+            expression::compile_expr(&context, &Expr::AggregateFunction {
+                name: String::from("count"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Int64,
+            }, &schema).unwrap(),
+        ];
+
+        let aggr_schema = Arc::new(Schema::new(vec![
+            Field::new("category", DataType::Int32, false),
+            Field::new("min_amount", DataType::Int32, true),
+            Field::new("count", DataType::Int64, false),
+        ]));
+
+        let mut aggregate = AggregateRelation::new(
+            aggr_schema,
+            relation,
+            group_expr,
+            aggr_expr,
+            Rc::new(AggregateRegistry::new()),
+        );
+
+        let batch = aggregate.next().unwrap().unwrap();
+        assert_eq!(1, batch.num_rows());
+
+        let min_amounts = batch.column(1).as_any().downcast_ref::<Int32Array>().unwrap();
+        let counts = batch.column(2).as_any().downcast_ref::<Int64Array>().unwrap();
+
+        assert!(min_amounts.is_null(0));
+        assert_eq!(0, counts.value(0));
+    }
+
+    /// A trivial custom accumulator used to exercise the UDAF registration path: sums
+    /// every non-null value after doubling it
+    struct DoubleSumFunction {
+        data_type: DataType,
+        value: Option<ScalarValue>,
+    }
+
+    impl DoubleSumFunction {
+        fn new() -> Self {
+            Self { data_type: DataType::Float64, value: None }
+        }
+    }
+
+    impl AggregateFunction for DoubleSumFunction {
+        fn accumulate(&mut self, value: &Option<ScalarValue>) {
+            if let Some(value) = value {
+                let doubled = scalar_to_f64(value) * 2.0;
+                self.value = Some(match &self.value {
+                    Some(ScalarValue::Float64(current)) => ScalarValue::Float64(current + doubled),
+                    _ => ScalarValue::Float64(doubled),
+                });
+            }
+        }
+
+        fn result(&mut self) -> &Option<ScalarValue> {
+            &self.value
+        }
+
+        fn data_type(&self) -> &DataType {
+            &self.data_type
+        }
+    }
+
+    #[test]
+    fn udaf_registration_and_execution() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("amount", DataType::Int32, false),
+        ]));
+
+        // register_aggregate_function/aggregate_registry live on ExecutionContext so
+        // that expression compilation and execution resolve UDAF names against the
+        // same registry instance
+        let mut context = ExecutionContext::new();
+        let factory: AggregateFunctionFactory =
+            Rc::new(|| Box::new(DoubleSumFunction::new()) as Box<AggregateFunction>);
+        context.register_aggregate_function("double_sum", DataType::Float64, factory);
+        let registry = context.aggregate_registry();
+
+        let aggr_expr = vec![expression::compile_expr(&context, &Expr::AggregateFunction {
+            name: String::from("double_sum"),
+            args: vec![Expr::Column(0)],
+            return_type: DataType::Float64,
+        }, &schema).unwrap()];
+
+        let aggr_schema = Arc::new(Schema::new(vec![
+            Field::new("double_sum", DataType::Float64, false),
+        ]));
+
+        let batch = RecordBatch::new(schema.clone(), vec![
+            Arc::new(Int32Array::from(vec![1, 2, 3])) as ArrayRef,
+        ]);
+        let relation = Rc::new(RefCell::new(MemRelation {
+            schema: schema.clone(),
+            batches: vec![Some(batch)],
+        }));
+
+        let mut aggregate = AggregateRelation::new(aggr_schema, relation, vec![], aggr_expr, registry);
+        let batch = aggregate.next().unwrap().unwrap();
+        let result = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(12.0, result.value(0));
+        assert!(aggregate.next().unwrap().is_none());
+    }
+
+    #[test]
+    fn unregistered_udaf_is_an_error_not_a_panic() {
+        let registry = AggregateRegistry::new();
+        let err = registry.create("does_not_exist");
+        assert!(err.is_err());
+    }
+
 }
@@ -40,6 +40,17 @@ impl ProjectRelation {
             schema,
         }
     }
+
+    /// The input relation being projected, exposed so plan producers can recurse
+    /// into it
+    pub(crate) fn input(&self) -> &Rc<RefCell<Relation>> {
+        &self.input
+    }
+
+    /// The projection expressions, in output order
+    pub(crate) fn expr(&self) -> &[RuntimeExpr] {
+        &self.expr
+    }
 }
 
 impl Relation for ProjectRelation {
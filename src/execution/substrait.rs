@@ -0,0 +1,327 @@
+// Copyright 2018 Grove Enterprises LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Substrait round-trip serialization for `ProjectRelation` and `AggregateRelation`
+//!
+//! This mirrors the subset of the Substrait plan message schema (see
+//! https://substrait.io) needed to ship a projection or an aggregate built by this
+//! crate to another engine, or to reconstruct one received from elsewhere: a single
+//! `ProjectRel`/`AggregateRel` node whose expressions reference input fields by index
+//! and whose aggregate measures are keyed by function name, the same way a Substrait
+//! function-extension registry resolves a measure to an implementation.
+//!
+//! A `Rel` does not carry its input: the producers translate only the relation they
+//! are given, not the relations beneath it, and `rel_to_relation` attaches the
+//! reconstructed relation to an input relation passed in by the caller.
+//!
+//! A `RuntimeExpr` has already been compiled down to closures by the time it reaches
+//! `ProjectRelation`/`AggregateRelation`, so it cannot be introspected back into an
+//! expression tree. The producers below rebuild output field references from the
+//! expression's name (via `get_name()`), which is all a projection needs; an
+//! aggregate's argument field cannot be recovered this way, so callers must supply it
+//! alongside the relation.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::datatypes::{Field, Schema};
+
+use crate::logicalplan::Expr;
+
+use super::aggregate::{AggregateRegistry, AggregateRelation};
+use super::context::ExecutionContext;
+use super::error::{ExecutionError, Result};
+use super::expression::{self, AggregateType, RuntimeExpr};
+use super::projection::ProjectRelation;
+use super::relation::Relation;
+
+/// The Substrait relation kinds this module knows how to produce and consume
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rel {
+    /// Mirrors a Substrait `ProjectRel`: one expression per output column
+    Project { expressions: Vec<Expr> },
+    /// Mirrors a Substrait `AggregateRel`: grouping keys plus aggregate measures
+    Aggregate {
+        groupings: Vec<Expr>,
+        measures: Vec<Expr>,
+    },
+}
+
+/// Translate a `ProjectRelation` into its Substrait `ProjectRel` equivalent
+pub fn project_to_rel(relation: &ProjectRelation, input_schema: &Schema) -> Result<Rel> {
+    let expressions = relation
+        .expr()
+        .iter()
+        .map(|e| column_reference(&e.get_name(), input_schema))
+        .collect::<Result<Vec<Expr>>>()?;
+    Ok(Rel::Project { expressions })
+}
+
+/// Translate an `AggregateRelation` into its Substrait `AggregateRel` equivalent.
+///
+/// `arg_columns` must have one entry per measure in `relation.aggr_expr()`, giving the
+/// input field index each measure's argument reads from - see the module docs for why
+/// this cannot be derived from the compiled `RuntimeExpr` itself.
+pub fn aggregate_to_rel(
+    relation: &AggregateRelation,
+    input_schema: &Schema,
+    arg_columns: &[usize],
+) -> Result<Rel> {
+    let groupings = relation
+        .group_expr()
+        .iter()
+        .map(|e| column_reference(&e.get_name(), input_schema))
+        .collect::<Result<Vec<Expr>>>()?;
+
+    if arg_columns.len() != relation.aggr_expr().len() {
+        return Err(ExecutionError::General(
+            "arg_columns must have one entry per aggregate measure".to_string(),
+        ));
+    }
+
+    let measures = relation
+        .aggr_expr()
+        .iter()
+        .zip(arg_columns.iter())
+        .map(|(e, &col)| match e {
+            RuntimeExpr::AggregateFunction { f, t, distinct, .. } => {
+                if *distinct {
+                    Err(ExecutionError::NotImplemented(
+                        "Substrait round-trip for DISTINCT aggregates is not yet supported".to_string(),
+                    ))
+                } else if let AggregateType::PercentileCont(_) | AggregateType::PercentileDisc(_) = f {
+                    // The Substrait expression carries only a function name and
+                    // column args, with nowhere to encode the percentile fraction `p`,
+                    // so round-tripping one of these would silently lose it. Reject
+                    // rather than emit a plan that can't reconstruct its own argument.
+                    Err(ExecutionError::NotImplemented(
+                        "Substrait round-trip for PERCENTILE_CONT/PERCENTILE_DISC is not yet supported".to_string(),
+                    ))
+                } else {
+                    Ok(Expr::AggregateFunction {
+                        name: aggregate_function_name(f),
+                        args: vec![Expr::Column(col)],
+                        return_type: t.clone(),
+                    })
+                }
+            }
+            _ => Err(ExecutionError::General("Expected an aggregate expression".to_string())),
+        })
+        .collect::<Result<Vec<Expr>>>()?;
+
+    Ok(Rel::Aggregate { groupings, measures })
+}
+
+/// Reconstruct the `Relation` tree described by `rel`, wired up to `input`
+pub fn rel_to_relation(
+    rel: &Rel,
+    input: Rc<RefCell<Relation>>,
+    input_schema: &Schema,
+    context: &ExecutionContext,
+    registry: Rc<AggregateRegistry>,
+) -> Result<Rc<RefCell<Relation>>> {
+    match rel {
+        Rel::Project { expressions } => {
+            let expr = expressions
+                .iter()
+                .map(|e| expression::compile_expr(context, e, input_schema))
+                .collect::<Result<Vec<RuntimeExpr>>>()?;
+
+            let schema = Arc::new(Schema::new(
+                expr.iter()
+                    .map(|e| Field::new(&e.get_name(), e.get_type(), true))
+                    .collect(),
+            ));
+
+            Ok(Rc::new(RefCell::new(ProjectRelation::new(input, expr, schema))) as Rc<RefCell<Relation>>)
+        }
+        Rel::Aggregate { groupings, measures } => {
+            let group_expr = groupings
+                .iter()
+                .map(|e| expression::compile_expr(context, e, input_schema))
+                .collect::<Result<Vec<RuntimeExpr>>>()?;
+
+            let aggr_expr = measures
+                .iter()
+                .map(|e| expression::compile_expr(context, e, input_schema))
+                .collect::<Result<Vec<RuntimeExpr>>>()?;
+
+            let schema = Arc::new(Schema::new(
+                group_expr
+                    .iter()
+                    .chain(aggr_expr.iter())
+                    .map(|e| Field::new(&e.get_name(), e.get_type(), true))
+                    .collect(),
+            ));
+
+            Ok(Rc::new(RefCell::new(AggregateRelation::new(
+                schema, input, group_expr, aggr_expr, registry,
+            ))) as Rc<RefCell<Relation>>)
+        }
+    }
+}
+
+/// The Substrait function-extension name a built-in `AggregateType` resolves to. A
+/// `Udaf` measure already carries its own name
+fn aggregate_function_name(t: &AggregateType) -> String {
+    match t {
+        AggregateType::Min => "min".to_string(),
+        AggregateType::Max => "max".to_string(),
+        AggregateType::Sum => "sum".to_string(),
+        AggregateType::Count => "count".to_string(),
+        AggregateType::Avg => "avg".to_string(),
+        AggregateType::PercentileCont(_) => "percentile_cont".to_string(),
+        AggregateType::PercentileDisc(_) => "percentile_disc".to_string(),
+        AggregateType::Mode => "mode".to_string(),
+        AggregateType::Udaf(name) => name.clone(),
+    }
+}
+
+fn column_reference(name: &str, schema: &Schema) -> Result<Expr> {
+    schema
+        .fields()
+        .iter()
+        .position(|f| f.name() == name)
+        .map(Expr::Column)
+        .ok_or_else(|| {
+            ExecutionError::General(format!(
+                "Column '{}' not found while producing a Substrait plan",
+                name
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::datasource::CsvDataSource;
+    use super::super::relation::DataSourceRelation;
+    use arrow::array::Float64Array;
+    use arrow::csv;
+    use arrow::datatypes::DataType;
+    use std::fs::File;
+
+    fn cities_schema() -> Arc<Schema> {
+        Arc::new(Schema::new(vec![
+            Field::new("name", DataType::Utf8, false),
+            Field::new("lat", DataType::Float64, false),
+            Field::new("lng", DataType::Float64, false),
+        ]))
+    }
+
+    fn load_cities() -> Rc<RefCell<Relation>> {
+        let schema = cities_schema();
+        let file = File::open("test/data/uk_cities.csv").unwrap();
+        let arrow_csv_reader = csv::Reader::new(file, schema.clone(), true, 1024, None);
+        let ds = CsvDataSource::new(schema.clone(), arrow_csv_reader);
+        Rc::new(RefCell::new(DataSourceRelation::new(Rc::new(RefCell::new(ds)))))
+    }
+
+    #[test]
+    fn round_trip_min_aggregate() {
+        let schema = cities_schema();
+        let context = ExecutionContext::new();
+
+        let aggr_expr = vec![expression::compile_expr(
+            &context,
+            &Expr::AggregateFunction {
+                name: String::from("min"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Float64,
+            },
+            &schema,
+        ).unwrap()];
+
+        let aggr_schema = Arc::new(Schema::new(vec![Field::new("min_lat", DataType::Float64, false)]));
+
+        let registry = Rc::new(AggregateRegistry::new());
+        let original = AggregateRelation::new(
+            aggr_schema.clone(),
+            load_cities(),
+            vec![],
+            aggr_expr,
+            registry.clone(),
+        );
+
+        let rel = aggregate_to_rel(&original, &schema, &[1]).unwrap();
+
+        let relation = rel_to_relation(&rel, load_cities(), &schema, &context, registry).unwrap();
+        let mut relation = relation.borrow_mut();
+        let batch = relation.next().unwrap().unwrap();
+        let min_lat = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(50.376289, min_lat.value(0));
+    }
+
+    #[test]
+    fn round_trip_max_aggregate() {
+        let schema = cities_schema();
+        let context = ExecutionContext::new();
+
+        let aggr_expr = vec![expression::compile_expr(
+            &context,
+            &Expr::AggregateFunction {
+                name: String::from("max"),
+                args: vec![Expr::Column(1)],
+                return_type: DataType::Float64,
+            },
+            &schema,
+        ).unwrap()];
+
+        let aggr_schema = Arc::new(Schema::new(vec![Field::new("max_lat", DataType::Float64, false)]));
+
+        let registry = Rc::new(AggregateRegistry::new());
+        let original = AggregateRelation::new(
+            aggr_schema.clone(),
+            load_cities(),
+            vec![],
+            aggr_expr,
+            registry.clone(),
+        );
+
+        let rel = aggregate_to_rel(&original, &schema, &[1]).unwrap();
+
+        let relation = rel_to_relation(&rel, load_cities(), &schema, &context, registry).unwrap();
+        let mut relation = relation.borrow_mut();
+        let batch = relation.next().unwrap().unwrap();
+        let max_lat = batch.column(0).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(57.477772, max_lat.value(0));
+    }
+
+    #[test]
+    fn round_trip_projection() {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("first_name", DataType::Utf8, false),
+        ]));
+        let context = ExecutionContext::new();
+
+        let projection_expr = vec![expression::compile_expr(&context, &Expr::Column(0), &schema).unwrap()];
+
+        let ds = CsvDataSource::new("test/data/people.csv", schema.clone(), 1024);
+        let relation = Rc::new(RefCell::new(DataSourceRelation::new(Rc::new(RefCell::new(ds)))));
+        let original = ProjectRelation::new(relation.clone(), projection_expr, schema.clone());
+
+        let rel = project_to_rel(&original, &schema).unwrap();
+
+        let ds = CsvDataSource::new("test/data/people.csv", schema.clone(), 1024);
+        let relation = Rc::new(RefCell::new(DataSourceRelation::new(Rc::new(RefCell::new(ds)))));
+        let registry = Rc::new(AggregateRegistry::new());
+        let reconstructed = rel_to_relation(&rel, relation, &schema, &context, registry).unwrap();
+        let mut reconstructed = reconstructed.borrow_mut();
+        let batch = reconstructed.next().unwrap().unwrap();
+        assert_eq!(1, batch.num_columns());
+        assert_eq!("id", batch.schema().field(0).name());
+    }
+}